@@ -0,0 +1,295 @@
+//! Guest ELF64 core dump, for post-mortem analysis of a fatal VCPU fault.
+//!
+//! Layout mirrors what a native Linux core file looks like to `gdb`: a
+//! `PT_NOTE` segment holding one `NT_PRSTATUS` per CPU, followed by one
+//! `PT_LOAD` segment per mapped guest-physical region, copied straight out
+//! of the backing host pages.
+
+use core::mem::size_of;
+
+use crate::cell::Cell;
+use crate::error::HvResult;
+use crate::header::HvHeader;
+use crate::percpu::{CpuState, PerCpu};
+
+const EI_NIDENT: usize = 16;
+const ET_CORE: u16 = 4;
+const EM_X86_64: u16 = 62;
+const PT_LOAD: u32 = 1;
+const PT_NOTE: u32 = 4;
+const NT_PRSTATUS: u32 = 1;
+
+#[repr(C)]
+struct Elf64Ehdr {
+    e_ident: [u8; EI_NIDENT],
+    e_type: u16,
+    e_machine: u16,
+    e_version: u32,
+    e_entry: u64,
+    e_phoff: u64,
+    e_shoff: u64,
+    e_flags: u32,
+    e_ehsize: u16,
+    e_phentsize: u16,
+    e_phnum: u16,
+    e_shentsize: u16,
+    e_shnum: u16,
+    e_shstrndx: u16,
+}
+
+#[repr(C)]
+struct Elf64Phdr {
+    p_type: u32,
+    p_flags: u32,
+    p_offset: u64,
+    p_vaddr: u64,
+    p_paddr: u64,
+    p_filesz: u64,
+    p_memsz: u64,
+    p_align: u64,
+}
+
+#[repr(C)]
+struct Elf64Nhdr {
+    n_namesz: u32,
+    n_descsz: u32,
+    n_type: u32,
+}
+
+/// `struct elf_prstatus.pr_reg`: general registers plus RIP/RSP/RFLAGS, in
+/// the order a debugger reading a Linux x86-64 core file expects.
+#[repr(C)]
+#[derive(Default)]
+struct PrStatusRegs {
+    r15: u64,
+    r14: u64,
+    r13: u64,
+    r12: u64,
+    rbp: u64,
+    rbx: u64,
+    r11: u64,
+    r10: u64,
+    r9: u64,
+    r8: u64,
+    rax: u64,
+    rcx: u64,
+    rdx: u64,
+    rsi: u64,
+    rdi: u64,
+    orig_rax: u64,
+    rip: u64,
+    cs: u64,
+    rflags: u64,
+    rsp: u64,
+    ss: u64,
+    fs_base: u64,
+    gs_base: u64,
+    ds: u64,
+    es: u64,
+    fs: u64,
+    gs: u64,
+}
+
+/// `struct timeval` as laid out in a Linux x86-64 `elf_prstatus`: two
+/// `long`s, no padding.
+#[repr(C)]
+#[derive(Default)]
+struct Timeval {
+    tv_sec: u64,
+    tv_usec: u64,
+}
+
+/// `struct elf_siginfo` embedded at the start of `elf_prstatus`.
+#[repr(C)]
+#[derive(Default)]
+struct ElfSiginfo {
+    si_signo: u32,
+    si_code: u32,
+    si_errno: u32,
+}
+
+/// Full Linux x86-64 `struct elf_prstatus`, 336 bytes with `pr_reg` (the
+/// actual register file gdb reads) at offset 112. We don't model a real
+/// guest process/thread/signal state, so everything but `pr_reg` is left
+/// zeroed; gdb only ever looks at `pr_reg` for register values.
+#[repr(C)]
+#[derive(Default)]
+struct Elf64Prstatus {
+    pr_info: ElfSiginfo,      // 12 bytes
+    pr_cursig: u16,           // 2 bytes
+    _pad0: u16,               // 2 bytes, aligns pr_sigpend to 8
+    pr_sigpend: u64,          // 8 bytes
+    pr_sighold: u64,          // 8 bytes
+    pr_pid: u32,              // 4 bytes
+    pr_ppid: u32,             // 4 bytes
+    pr_pgrp: u32,             // 4 bytes
+    pr_sid: u32,              // 4 bytes
+    pr_utime: Timeval,        // 16 bytes
+    pr_stime: Timeval,        // 16 bytes
+    pr_cutime: Timeval,       // 16 bytes
+    pr_cstime: Timeval,       // 16 bytes
+    pr_reg: PrStatusRegs,     // 216 bytes, starts at offset 112
+    pr_fpvalid: u32,          // 4 bytes
+    _pad1: u32,               // 4 bytes, pads struct to an 8-byte multiple
+}
+
+/// Where the produced core file bytes go: a debug console, a reserved
+/// memory buffer, or (later) a virtio device.
+pub trait CoreDumpSink {
+    fn write(&mut self, buf: &[u8]) -> HvResult;
+}
+
+unsafe fn as_bytes<T>(val: &T) -> &[u8] {
+    core::slice::from_raw_parts(val as *const T as *const u8, size_of::<T>())
+}
+
+/// Write an ELF64 core dump of the whole guest to `sink`.
+///
+/// `cpu` is the VCPU that took the fault; its cell (not necessarily cell
+/// 0's) is what gets walked for guest-physical regions and host-page
+/// lookups, so a fault on any cell in a multi-cell system dumps the right
+/// guest. Standalone entry point so it can be invoked from
+/// `PerCpu::fault_with_coredump()` on a fatal error, or from an external
+/// debug command.
+pub fn dump_guest_core<S: CoreDumpSink>(cpu: &PerCpu, sink: &mut S) -> HvResult {
+    let max_cpus = HvHeader::get().max_cpus as usize;
+    let cell = cpu.vcpu.cell();
+
+    // Only CPUs that have actually run `init`/`activate_vmm` have
+    // meaningful VMCS state; skip the rest the same way `Debug for
+    // PerCpu` does.
+    let active_cpus: alloc::vec::Vec<usize> = (0..max_cpus)
+        .filter(|&id| PerCpu::from_id(id).state != CpuState::HvDisabled)
+        .collect();
+
+    let notes_size = active_cpus.len()
+        * (size_of::<Elf64Nhdr>() + 8 /* "CORE\0\0\0\0" */ + size_of::<Elf64Prstatus>());
+
+    let regions = guest_memory_regions(cell);
+    let phnum = 1 + regions.len();
+    let phoff = size_of::<Elf64Ehdr>() as u64;
+    let mut data_off = phoff + phnum as u64 * size_of::<Elf64Phdr>() as u64;
+
+    let mut e_ident = [0u8; EI_NIDENT];
+    e_ident[0..4].copy_from_slice(&[0x7f, b'E', b'L', b'F']);
+    e_ident[4] = 2; // ELFCLASS64
+    e_ident[5] = 1; // little-endian
+    e_ident[6] = 1; // EV_CURRENT
+
+    let ehdr = Elf64Ehdr {
+        e_ident,
+        e_type: ET_CORE,
+        e_machine: EM_X86_64,
+        e_version: 1,
+        e_entry: 0,
+        e_phoff: phoff,
+        e_shoff: 0,
+        e_flags: 0,
+        e_ehsize: size_of::<Elf64Ehdr>() as u16,
+        e_phentsize: size_of::<Elf64Phdr>() as u16,
+        e_phnum: phnum as u16,
+        e_shentsize: 0,
+        e_shnum: 0,
+        e_shstrndx: 0,
+    };
+    sink.write(unsafe { as_bytes(&ehdr) })?;
+
+    let note_off = data_off;
+    sink.write(unsafe {
+        as_bytes(&Elf64Phdr {
+            p_type: PT_NOTE,
+            p_flags: 0,
+            p_offset: note_off,
+            p_vaddr: 0,
+            p_paddr: 0,
+            p_filesz: notes_size as u64,
+            p_memsz: 0,
+            p_align: 4,
+        })
+    })?;
+    data_off += notes_size as u64;
+
+    for region in &regions {
+        sink.write(unsafe {
+            as_bytes(&Elf64Phdr {
+                p_type: PT_LOAD,
+                p_flags: 7, // RWX; exact perms aren't load-bearing for a core file
+                p_offset: data_off,
+                p_vaddr: region.gpa as u64,
+                p_paddr: region.gpa as u64,
+                p_filesz: region.size as u64,
+                p_memsz: region.size as u64,
+                p_align: 0x1000,
+            })
+        })?;
+        data_off += region.size as u64;
+    }
+
+    for &cpu_id in &active_cpus {
+        let cpu = PerCpu::from_id(cpu_id);
+        let state = cpu.guest_all_state();
+        let regs = cpu.guest_regs();
+        let prstatus = Elf64Prstatus {
+            pr_reg: PrStatusRegs {
+                r15: regs.r15 as _,
+                r14: regs.r14 as _,
+                r13: regs.r13 as _,
+                r12: regs.r12 as _,
+                rbp: regs.rbp as _,
+                rbx: regs.rbx as _,
+                r11: regs.r11 as _,
+                r10: regs.r10 as _,
+                r9: regs.r9 as _,
+                r8: regs.r8 as _,
+                rax: regs.rax as _,
+                rcx: regs.rcx as _,
+                rdx: regs.rdx as _,
+                rsi: regs.rsi as _,
+                rdi: regs.rdi as _,
+                rip: state.rip,
+                cs: state.cs.selector as _,
+                rflags: state.rflags,
+                rsp: state.rsp,
+                ss: state.ss.selector as _,
+                ..Default::default()
+            },
+            ..Default::default()
+        };
+        sink.write(unsafe {
+            as_bytes(&Elf64Nhdr {
+                n_namesz: 5,
+                n_descsz: size_of::<Elf64Prstatus>() as u32,
+                n_type: NT_PRSTATUS,
+            })
+        })?;
+        sink.write(b"CORE\0\0\0\0")?;
+        sink.write(unsafe { as_bytes(&prstatus) })?;
+    }
+
+    for region in &regions {
+        let hva = cell.gpa_to_hva(region.gpa)?;
+        let bytes = unsafe { core::slice::from_raw_parts(hva as *const u8, region.size) };
+        sink.write(bytes)?;
+    }
+
+    Ok(())
+}
+
+struct GuestMemoryRegion {
+    gpa: usize,
+    size: usize,
+}
+
+/// Enumerate the guest-physical regions mapped by `cell`'s second-stage
+/// page table.
+fn guest_memory_regions(cell: &Cell) -> alloc::vec::Vec<GuestMemoryRegion> {
+    cell.gpm
+        .read()
+        .regions()
+        .iter()
+        .map(|r| GuestMemoryRegion {
+            gpa: r.start,
+            size: r.size,
+        })
+        .collect()
+}
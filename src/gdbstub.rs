@@ -0,0 +1,230 @@
+//! Remote debugging support (GDB/LLDB) for guest VCPUs.
+//!
+//! A host-side debugger attaches over a serial or shared-memory transport
+//! and drives the guest through the [`Debuggable`] trait: reading/writing
+//! the general register file, inspecting guest memory, single-stepping,
+//! and setting breakpoints. Memory and breakpoint addresses are guest
+//! virtual addresses, which the [`PerCpu`] implementation translates itself
+//! through [`PerCpu::translate_gva`](crate::percpu::PerCpu::translate_gva).
+
+use alloc::collections::BTreeMap;
+
+use crate::error::{HvError, HvResult};
+use crate::memory::MemFlags;
+use crate::percpu::PerCpu;
+
+/// The original byte replaced by a software breakpoint's `0xCC`.
+type OriginalByte = u8;
+
+/// x86-64 GPRs in the order GDB's `g`/`G` packets expect them.
+#[derive(Debug, Default, Clone, Copy)]
+#[repr(C)]
+pub struct GdbCoreRegs {
+    pub rax: u64,
+    pub rbx: u64,
+    pub rcx: u64,
+    pub rdx: u64,
+    pub rsi: u64,
+    pub rdi: u64,
+    pub rbp: u64,
+    pub rsp: u64,
+    pub r8: u64,
+    pub r9: u64,
+    pub r10: u64,
+    pub r11: u64,
+    pub r12: u64,
+    pub r13: u64,
+    pub r14: u64,
+    pub r15: u64,
+    pub rip: u64,
+    pub rflags: u64,
+    pub cs: u64,
+    pub ss: u64,
+    pub ds: u64,
+    pub es: u64,
+    pub fs: u64,
+    pub gs: u64,
+}
+
+/// Why a debug exception was taken, so the stub can decide how to report it.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum StopReason {
+    SingleStep,
+    SoftwareBreakpoint,
+    HardwareBreakpoint(usize),
+}
+
+/// Per-VCPU debugger state: pending single-step request and the software
+/// breakpoints currently patched into guest memory.
+#[derive(Default)]
+pub struct DebugState {
+    pub single_step: bool,
+    software_breakpoints: BTreeMap<usize, OriginalByte>,
+}
+
+/// Operations a host-side debugger needs from a guest VCPU. Implemented
+/// for [`PerCpu`] in terms of this hypervisor's own guest-state accessors.
+pub trait Debuggable {
+    fn read_regs(&self) -> HvResult<GdbCoreRegs>;
+    fn write_regs(&mut self, regs: &GdbCoreRegs) -> HvResult;
+    fn read_mem(&self, gva: usize, buf: &mut [u8]) -> HvResult;
+    fn write_mem(&mut self, gva: usize, buf: &[u8]) -> HvResult;
+    fn set_single_step(&mut self, enabled: bool) -> HvResult;
+    fn set_hw_breakpoint(&mut self, slot: usize, gva: usize) -> HvResult;
+    fn clear_hw_breakpoint(&mut self, slot: usize) -> HvResult;
+    fn set_sw_breakpoint(&mut self, gva: usize) -> HvResult;
+    fn clear_sw_breakpoint(&mut self, gva: usize) -> HvResult;
+}
+
+impl Debuggable for PerCpu {
+    fn read_regs(&self) -> HvResult<GdbCoreRegs> {
+        let regs = self.guest_regs();
+        let state = self.guest_all_state();
+        Ok(GdbCoreRegs {
+            rax: regs.rax as _,
+            rbx: regs.rbx as _,
+            rcx: regs.rcx as _,
+            rdx: regs.rdx as _,
+            rsi: regs.rsi as _,
+            rdi: regs.rdi as _,
+            rbp: regs.rbp as _,
+            rsp: state.rsp,
+            r8: regs.r8 as _,
+            r9: regs.r9 as _,
+            r10: regs.r10 as _,
+            r11: regs.r11 as _,
+            r12: regs.r12 as _,
+            r13: regs.r13 as _,
+            r14: regs.r14 as _,
+            r15: regs.r15 as _,
+            rip: state.rip,
+            rflags: state.rflags,
+            cs: state.cs.selector as _,
+            ss: state.ss.selector as _,
+            ds: state.ds.selector as _,
+            es: state.es.selector as _,
+            fs: state.fs.selector as _,
+            gs: state.gs.selector as _,
+        })
+    }
+
+    fn write_regs(&mut self, regs: &GdbCoreRegs) -> HvResult {
+        let gregs = self.guest_regs_mut();
+        gregs.rax = regs.rax as _;
+        gregs.rbx = regs.rbx as _;
+        gregs.rcx = regs.rcx as _;
+        gregs.rdx = regs.rdx as _;
+        gregs.rsi = regs.rsi as _;
+        gregs.rdi = regs.rdi as _;
+        gregs.rbp = regs.rbp as _;
+        gregs.r8 = regs.r8 as _;
+        gregs.r9 = regs.r9 as _;
+        gregs.r10 = regs.r10 as _;
+        gregs.r11 = regs.r11 as _;
+        gregs.r12 = regs.r12 as _;
+        gregs.r13 = regs.r13 as _;
+        gregs.r14 = regs.r14 as _;
+        gregs.r15 = regs.r15 as _;
+
+        let mut state = self.guest_all_state_mut();
+        state.set_rsp(regs.rsp);
+        state.set_rip(regs.rip);
+        state.set_rflags(regs.rflags);
+        Ok(())
+    }
+
+    fn read_mem(&self, gva: usize, buf: &mut [u8]) -> HvResult {
+        let mut off = 0;
+        while off < buf.len() {
+            let (gpa, flags) = self.translate_gva(gva + off)?;
+            if !flags.contains(MemFlags::READ) {
+                return Err(HvError::BadState);
+            }
+            let chunk = core::cmp::min(buf.len() - off, 0x1000 - (gpa & 0xfff));
+            let hva = self.vcpu.cell().gpa_to_hva(gpa)?;
+            unsafe {
+                core::ptr::copy_nonoverlapping(
+                    hva as *const u8,
+                    buf[off..].as_mut_ptr(),
+                    chunk,
+                );
+            }
+            off += chunk;
+        }
+        Ok(())
+    }
+
+    fn write_mem(&mut self, gva: usize, buf: &[u8]) -> HvResult {
+        let mut off = 0;
+        while off < buf.len() {
+            let (gpa, flags) = self.translate_gva(gva + off)?;
+            if !flags.contains(MemFlags::WRITE) {
+                return Err(HvError::BadState);
+            }
+            let chunk = core::cmp::min(buf.len() - off, 0x1000 - (gpa & 0xfff));
+            let hva = self.vcpu.cell().gpa_to_hva(gpa)?;
+            unsafe {
+                core::ptr::copy_nonoverlapping(buf[off..].as_ptr(), hva as *mut u8, chunk);
+            }
+            off += chunk;
+        }
+        Ok(())
+    }
+
+    fn set_single_step(&mut self, enabled: bool) -> HvResult {
+        const RFLAGS_TF: u64 = 1 << 8;
+        let mut state = self.guest_all_state_mut();
+        let rflags = state.rflags();
+        state.set_rflags(if enabled {
+            rflags | RFLAGS_TF
+        } else {
+            rflags & !RFLAGS_TF
+        });
+        self.debug.single_step = enabled;
+        Ok(())
+    }
+
+    fn set_hw_breakpoint(&mut self, slot: usize, gva: usize) -> HvResult {
+        self.vcpu.set_debug_addr_reg(slot, gva)
+    }
+
+    fn clear_hw_breakpoint(&mut self, slot: usize) -> HvResult {
+        self.vcpu.clear_debug_addr_reg(slot)
+    }
+
+    fn set_sw_breakpoint(&mut self, gva: usize) -> HvResult {
+        if self.debug.software_breakpoints.contains_key(&gva) {
+            return Ok(());
+        }
+        let mut orig = [0u8; 1];
+        self.read_mem(gva, &mut orig)?;
+        self.poke_guest_byte(gva, 0xcc)?;
+        self.debug.software_breakpoints.insert(gva, orig[0]);
+        Ok(())
+    }
+
+    fn clear_sw_breakpoint(&mut self, gva: usize) -> HvResult {
+        if let Some(orig) = self.debug.software_breakpoints.remove(&gva) {
+            self.poke_guest_byte(gva, orig)?;
+        }
+        Ok(())
+    }
+}
+
+impl PerCpu {
+    /// Patch a single byte of guest code for a software breakpoint.
+    ///
+    /// This is a host-privileged write, not an emulated guest store: it's
+    /// how the debugger itself pokes `0xCC` into ordinary R+X guest text
+    /// that the guest never maps writable, so unlike [`Debuggable::write_mem`]
+    /// it only requires the page to be present and readable.
+    fn poke_guest_byte(&mut self, gva: usize, byte: u8) -> HvResult {
+        let (gpa, flags) = self.translate_gva(gva)?;
+        if !flags.contains(MemFlags::READ) {
+            return Err(HvError::BadState);
+        }
+        let hva = self.vcpu.cell().gpa_to_hva(gpa)?;
+        unsafe { core::ptr::write_volatile(hva as *mut u8, byte) };
+        Ok(())
+    }
+}
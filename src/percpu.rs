@@ -1,5 +1,5 @@
 use core::fmt::{Debug, Formatter, Result};
-use core::sync::atomic::{AtomicIsize, Ordering};
+use core::sync::atomic::{AtomicBool, AtomicIsize, Ordering};
 
 use crate::arch::{GuestRegisters, LinuxContext};
 use crate::arch::{HostPageTable, Vcpu, VcpuGuestState, VcpuGuestStateMut};
@@ -7,11 +7,17 @@ use crate::cell::Cell;
 use crate::consts::{HV_STACK_SIZE, LOCAL_PER_CPU_BASE};
 use crate::error::HvResult;
 use crate::ffi::PER_CPU_ARRAY_PTR;
+use crate::gdbstub::DebugState;
 use crate::header::HvHeader;
 use crate::memory::{addr::virt_to_phys, GenericPageTable, MemFlags, MemoryRegion, MemorySet};
 
 pub const PER_CPU_SIZE: usize = core::mem::size_of::<PerCpu>();
 
+/// IPI vector used to ask a CPU to park itself in [`PerCpu::park`]'s spin
+/// loop instead of re-entering the guest. Handled in the VM-exit path
+/// alongside the other IPI vectors.
+pub const PARK_IPI_VECTOR: u8 = 0xf1;
+
 static ACTIVATED_CPUS: AtomicIsize = AtomicIsize::new(0);
 
 #[derive(Debug, Eq, PartialEq)]
@@ -20,14 +26,29 @@ pub enum CpuState {
     HvEnabled,
 }
 
+/// Why a CPU is currently parked, surfaced through `Debug for PerCpu`.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum ParkReason {
+    Debugger,
+    Suspend,
+}
+
 #[repr(align(4096))]
 pub struct PerCpu {
     pub cpu_id: usize,
     pub state: CpuState,
     pub vcpu: Vcpu,
     stack: [u8; HV_STACK_SIZE],
-    linux: LinuxContext,
+    pub(crate) linux: LinuxContext,
     hvm: MemorySet<HostPageTable>,
+    pub(crate) debug: DebugState,
+    /// Set by another CPU to request this one park; cleared by `unpark()`.
+    /// Acquire/release on this flag is what makes the stop race-free
+    /// relative to VM-entry: a CPU checks it (Acquire) right before
+    /// entering the guest, and `park()` sets it (Release) before sending
+    /// the IPI.
+    park_requested: AtomicBool,
+    park_reason: spin::Mutex<Option<ParkReason>>,
 }
 
 impl PerCpu {
@@ -73,7 +94,7 @@ impl PerCpu {
         VcpuGuestState::from(self)
     }
 
-    fn _guest_all_state_mut(&self) -> VcpuGuestStateMut {
+    pub(crate) fn guest_all_state_mut(&self) -> VcpuGuestStateMut {
         VcpuGuestStateMut::from(self)
     }
 
@@ -87,6 +108,9 @@ impl PerCpu {
         self.cpu_id = cpu_id;
         self.state = CpuState::HvDisabled;
         self.linux = LinuxContext::load_from(linux_sp);
+        self.debug = DebugState::default();
+        self.park_requested = AtomicBool::new(false);
+        self.park_reason = spin::Mutex::new(None);
 
         let mut hvm = cell.hvm.read().clone();
         let vaddr = self as *const _ as usize;
@@ -118,6 +142,7 @@ impl PerCpu {
 
     #[inline(never)]
     fn activate_vmm_local(&mut self) -> HvResult {
+        self.check_parked();
         self.vcpu.activate_vmm(&self.linux)?;
         unreachable!()
     }
@@ -167,9 +192,117 @@ impl PerCpu {
         common_cpu_data.deactivate_vmm_common()
     }
 
+    /// Request that this CPU park instead of re-entering the guest, and
+    /// send the IPI that makes it notice. Returns immediately; the target
+    /// actually stops once the IPI lands and its VM-exit handler routes
+    /// [`PARK_IPI_VECTOR`] to [`Self::handle_ipi`], or (if it was already
+    /// outside the guest) the next time it passes through
+    /// [`Self::check_parked`].
+    pub fn park(&mut self, reason: ParkReason) -> HvResult {
+        *self.park_reason.lock() = Some(reason);
+        self.park_requested.store(true, Ordering::Release);
+        crate::arch::send_ipi(self.cpu_id, PARK_IPI_VECTOR);
+        Ok(())
+    }
+
+    /// Clear a pending park request and flush any guest-register edits a
+    /// debugger made while this CPU was parked, so the next VM-entry sees
+    /// them.
+    pub fn unpark(&mut self) -> HvResult {
+        self.vcpu.flush_guest_state(&self.guest_all_state())?;
+        *self.park_reason.lock() = None;
+        self.park_requested.store(false, Ordering::Release);
+        Ok(())
+    }
+
+    /// Spins until [`Self::unpark`] clears the request, never re-entering
+    /// the guest in the meantime. Called either from [`Self::handle_ipi`]
+    /// (the IPI arrived while we were running the guest) or from
+    /// [`Self::check_parked`] (we noticed the flag ourselves on the way
+    /// back in).
+    pub fn handle_park_ipi(&mut self) {
+        while self.park_requested.load(Ordering::Acquire) {
+            core::hint::spin_loop();
+        }
+    }
+
+    /// VM-exit-path IPI dispatch entry point: the arch-level interrupt
+    /// handler calls this for every vectored IPI it receives so that a CPU
+    /// spinning in the guest (not just one sitting at a pre-entry check)
+    /// actually parks as soon as another CPU asks it to, instead of only
+    /// noticing on its next unrelated VM-exit.
+    pub fn handle_ipi(&mut self, vector: u8) -> HvResult {
+        match vector {
+            PARK_IPI_VECTOR => {
+                self.handle_park_ipi();
+                Ok(())
+            }
+            _ => Err(crate::error::HvError::BadState),
+        }
+    }
+
+    /// The "check it right before entering the guest" half of the
+    /// park/unpark contract: called from every place that is about to
+    /// VM-enter ([`Self::activate_vmm_local`] and after handling a
+    /// VM-exit in [`Self::fault`]). A no-op unless another CPU called
+    /// [`Self::park`] on us, in which case it blocks here instead of
+    /// resuming the guest.
+    fn check_parked(&mut self) {
+        if self.park_requested.load(Ordering::Acquire) {
+            self.handle_park_ipi();
+        }
+    }
+
+    /// Block until every CPU the guest expects has activated the
+    /// hypervisor, i.e. `ACTIVATED_CPUS == HvHeader::get().max_cpus`.
+    pub fn wait_for_all_activated() {
+        while Self::activated_cpus() < HvHeader::get().max_cpus as usize {
+            core::hint::spin_loop();
+        }
+    }
+
     pub fn fault(&mut self) -> HvResult {
+        if self.vcpu.is_debug_exception() {
+            self.handle_debug_exception()?;
+            self.check_parked();
+            return Ok(());
+        }
         warn!("VCPU fault: {:#x?}", self);
         self.vcpu.inject_fault()?;
+        self.check_parked();
+        Ok(())
+    }
+
+    /// Like [`Self::fault`], but for a fault other than a debug exception
+    /// first writes an ELF64 core dump of the whole guest to `sink` so the
+    /// fault can be inspected post-mortem.
+    pub fn fault_with_coredump<S: crate::coredump::CoreDumpSink>(
+        &mut self,
+        sink: &mut S,
+    ) -> HvResult {
+        if self.vcpu.is_debug_exception() {
+            return self.fault();
+        }
+        warn!("VCPU fault: {:#x?}", self);
+        if let Err(e) = crate::coredump::dump_guest_core(self, sink) {
+            warn!("failed to write guest core dump: {:?}", e);
+        }
+        self.vcpu.inject_fault()?;
+        self.check_parked();
+        Ok(())
+    }
+
+    /// Handle a #DB taken for single-stepping or a hardware breakpoint.
+    ///
+    /// Parks every other activated CPU so the debugger sees a consistent
+    /// snapshot of the whole guest while this one is stopped.
+    fn handle_debug_exception(&mut self) -> HvResult {
+        for id in 0..HvHeader::get().max_cpus as usize {
+            if id != self.cpu_id && Self::from_id(id).state != CpuState::HvDisabled {
+                Self::from_id_mut(id).park(ParkReason::Debugger)?;
+            }
+        }
+        debug!("CPU {} stopped on debug exception", self.cpu_id);
         Ok(())
     }
 }
@@ -179,6 +312,9 @@ impl Debug for PerCpu {
         let mut res = f.debug_struct("PerCpu");
         res.field("cpu_id", &self.cpu_id)
             .field("state", &self.state);
+        if let Some(reason) = *self.park_reason.lock() {
+            res.field("parked", &reason);
+        }
         if self.state != CpuState::HvDisabled {
             res.field("guest_state", &self.guest_all_state());
         } else {
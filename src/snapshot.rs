@@ -0,0 +1,76 @@
+//! Snapshot/restore of per-CPU state, for suspend-resume and migration.
+//!
+//! A [`PerCpuSnapshot`] captures everything needed to hand control back to
+//! a frozen VCPU: the architectural state tracked by [`Vcpu`] and the
+//! [`LinuxContext`] used to return to the host kernel. A snapshot carries
+//! a version number that [`PerCpu::restore_state`] checks. It is an
+//! in-memory structure of native-endian integers; this module does not
+//! serialize it to bytes, so no byte order is fixed for it.
+
+use crate::arch::{LinuxContext, VcpuGuestState};
+use crate::error::HvResult;
+use crate::percpu::PerCpu;
+
+/// Bumped whenever the layout of [`PerCpuSnapshot`] changes in a way that
+/// isn't backward compatible.
+pub const SNAPSHOT_VERSION: u32 = 1;
+
+/// All per-CPU state needed to resume a guest VCPU on a later boot.
+///
+/// The struct is read and written as a whole through [`PerCpu::save_state`]
+/// and [`PerCpu::restore_state`]. Its fields are native integers; no
+/// little-endian or other byte-level encoding is provided here.
+#[derive(Debug, Clone)]
+pub struct PerCpuSnapshot {
+    pub version: u32,
+    pub cpu_id: usize,
+    pub guest_state: VcpuGuestState,
+    pub guest_regs: crate::arch::GuestRegisters,
+    pub linux: LinuxContext,
+}
+
+impl PerCpu {
+    /// Capture this CPU's full architectural state. Only valid while the
+    /// VMM is active (`self.state == CpuState::HvEnabled`): the VMCS
+    /// fields read by [`Self::guest_all_state`] aren't meaningful
+    /// otherwise.
+    pub fn save_state(&self) -> HvResult<PerCpuSnapshot> {
+        Ok(PerCpuSnapshot {
+            version: SNAPSHOT_VERSION,
+            cpu_id: self.cpu_id,
+            guest_state: self.guest_all_state(),
+            guest_regs: *self.guest_regs(),
+            linux: self.linux.clone(),
+        })
+    }
+
+    /// Repopulate this CPU's VMCS fields and guest registers from a
+    /// previously captured snapshot. Must be called before the next
+    /// VM-entry; the actual resume is left to the caller (e.g. after
+    /// re-running [`Self::init`] on the target boot).
+    pub fn restore_state(&mut self, snapshot: &PerCpuSnapshot) -> HvResult {
+        if snapshot.version != SNAPSHOT_VERSION {
+            return Err(crate::error::HvError::BadState);
+        }
+        self.cpu_id = snapshot.cpu_id;
+        self.linux = snapshot.linux.clone();
+        *self.guest_regs_mut() = snapshot.guest_regs;
+
+        let src = &snapshot.guest_state;
+        let mut state = self.guest_all_state_mut();
+        state.set_rip(src.rip);
+        state.set_rsp(src.rsp);
+        state.set_rflags(src.rflags);
+        state.set_cr0(src.cr0);
+        state.set_cr3(src.cr3);
+        state.set_cr4(src.cr4);
+        state.set_efer(src.efer);
+        state.set_cs(src.cs);
+        state.set_ss(src.ss);
+        state.set_ds(src.ds);
+        state.set_es(src.es);
+        state.set_fs(src.fs);
+        state.set_gs(src.gs);
+        Ok(())
+    }
+}
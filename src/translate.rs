@@ -0,0 +1,208 @@
+//! Guest virtual-to-physical address translation.
+//!
+//! Walks the guest's *own* paging structures (not the second-stage/EPT
+//! tables) to turn a guest virtual address into a guest physical one,
+//! without ever faulting the host. Used by the gdbstub memory and
+//! breakpoint accessors, and independently useful for emulating
+//! instructions that reference linear addresses.
+
+use crate::error::{HvError, HvResult};
+use crate::memory::MemFlags;
+use crate::percpu::PerCpu;
+
+const PAGE_SIZE: usize = 0x1000;
+const ENTRIES_PER_TABLE: usize = 512;
+
+const PTE_PRESENT: u64 = 1 << 0;
+const PTE_WRITABLE: u64 = 1 << 1;
+const PTE_USER: u64 = 1 << 2;
+const PTE_HUGE: u64 = 1 << 7;
+const PTE_NX: u64 = 1 << 63;
+const PTE_ADDR_MASK: u64 = 0x000f_ffff_ffff_f000;
+/// Bits 52:62 of a 64-bit page-table entry are reserved (above the
+/// supported physical address width, below the NX bit); a guest entry
+/// that sets any of them is malformed rather than merely non-present.
+const PTE_RESERVED_MASK: u64 = 0x7ff0_0000_0000_0000;
+/// Bits 1:2 and 5:8 of a PAE PDPTE are reserved and must be zero (bits 3:4
+/// are PWT/PCD, bits 9:11 are ignored).
+const PDPTE_RESERVED_LOW: u64 = 0x1e6;
+/// Bits 52:63 of a PAE PDPTE are reserved; unlike a long-mode PTE there's
+/// no NX bit at this level, so bit 63 is reserved too (not just 52:62).
+const PDPTE_RESERVED_HIGH: u64 = 0xfff0_0000_0000_0000;
+
+const CR0_PG: u64 = 1 << 31;
+const CR4_PAE: u64 = 1 << 5;
+const CR4_LA57: u64 = 1 << 12;
+const EFER_LMA: u64 = 1 << 10;
+
+impl PerCpu {
+    /// Walk the guest's page tables to translate `gva`, returning the
+    /// guest-physical address and the effective (accumulated) R/W/U/NX
+    /// flags. Returns an error rather than injecting a page fault into the
+    /// guest when an entry is non-present or has reserved bits set.
+    pub fn translate_gva(&self, gva: usize) -> HvResult<(usize, MemFlags)> {
+        let state = self.guest_all_state();
+        if state.cr0 & CR0_PG == 0 {
+            // Paging disabled: identity-mapped, fully accessible.
+            return Ok((
+                gva,
+                MemFlags::READ | MemFlags::WRITE | MemFlags::EXECUTE | MemFlags::USER,
+            ));
+        }
+
+        if state.cr4 & CR4_PAE == 0 {
+            self.translate_gva_32(gva, state.cr3 as usize)
+        } else if state.efer & EFER_LMA == 0 {
+            self.translate_gva_pae(gva, state.cr3 as usize)
+        } else if state.cr4 & CR4_LA57 != 0 {
+            self.translate_gva_long(gva, state.cr3 as usize, 5)
+        } else {
+            self.translate_gva_long(gva, state.cr3 as usize, 4)
+        }
+    }
+
+    fn read_guest_u64(&self, gpa: usize) -> HvResult<u64> {
+        let hva = self.vcpu.cell().gpa_to_hva(gpa)?;
+        Ok(unsafe { core::ptr::read_volatile(hva as *const u64) })
+    }
+
+    fn read_guest_u32(&self, gpa: usize) -> HvResult<u32> {
+        let hva = self.vcpu.cell().gpa_to_hva(gpa)?;
+        Ok(unsafe { core::ptr::read_volatile(hva as *const u32) })
+    }
+
+    /// 4-level (or 5-level) long-mode walk.
+    fn translate_gva_long(&self, gva: usize, cr3: usize, levels: u32) -> HvResult<(usize, MemFlags)> {
+        let mut table_gpa = cr3 & PTE_ADDR_MASK as usize;
+        let mut flags = MemFlags::READ | MemFlags::WRITE | MemFlags::EXECUTE | MemFlags::USER;
+
+        for level in (0..levels).rev() {
+            let shift = 12 + level * 9;
+            let index = (gva >> shift) & (ENTRIES_PER_TABLE - 1);
+            let entry = self.read_guest_u64(table_gpa + index * 8)?;
+            if entry & PTE_PRESENT == 0 {
+                return Err(HvError::BadState);
+            }
+            if entry & PTE_RESERVED_MASK != 0 {
+                return Err(HvError::BadState);
+            }
+            // PS is only architecturally defined at the PDPT (1GiB pages)
+            // and PD (2MiB pages) levels, i.e. level 1 and 2 regardless of
+            // whether this is a 4- or 5-level walk; a PML4E or PML5E
+            // (level >= 3) with it set is reserved.
+            if level >= 3 && entry & PTE_HUGE != 0 {
+                return Err(HvError::BadState);
+            }
+            accumulate_flags(&mut flags, entry);
+
+            if level > 0 && entry & PTE_HUGE != 0 {
+                let page_shift = 12 + level * 9;
+                let page_mask = (1usize << page_shift) - 1;
+                let gpa = (entry as usize & PTE_ADDR_MASK as usize & !page_mask) | (gva & page_mask);
+                return Ok((gpa, flags));
+            }
+            table_gpa = entry as usize & PTE_ADDR_MASK as usize;
+        }
+
+        Ok((table_gpa | (gva & (PAGE_SIZE - 1)), flags))
+    }
+
+    /// PAE (32-bit with CR4.PAE=1) walk: a 4-entry PDPT plus 2-level tables.
+    fn translate_gva_pae(&self, gva: usize, cr3: usize) -> HvResult<(usize, MemFlags)> {
+        let pdpt_gpa = cr3 & 0xffff_ffe0;
+        let pdpte = self.read_guest_u64(pdpt_gpa + ((gva >> 30) & 0x3) * 8)?;
+        if pdpte & PTE_PRESENT == 0 {
+            return Err(HvError::BadState);
+        }
+        // The PAE PDPTE has its own, narrower reserved-bit layout: PWT
+        // (bit 3) and PCD (bit 4) are defined, bits 9:11 are ignored, and
+        // there's no NX/huge bit, but bits 1:2 and 5:8 are reserved and
+        // must be zero.
+        if pdpte & (PDPTE_RESERVED_LOW | PDPTE_RESERVED_HIGH) != 0 {
+            return Err(HvError::BadState);
+        }
+
+        let mut flags = MemFlags::READ | MemFlags::WRITE | MemFlags::EXECUTE | MemFlags::USER;
+        let pd_gpa = pdpte as usize & PTE_ADDR_MASK as usize;
+        let pde = self.read_guest_u64(pd_gpa + ((gva >> 21) & 0x1ff) * 8)?;
+        if pde & PTE_PRESENT == 0 {
+            return Err(HvError::BadState);
+        }
+        if pde & PTE_RESERVED_MASK != 0 {
+            return Err(HvError::BadState);
+        }
+        accumulate_flags(&mut flags, pde);
+        if pde & PTE_HUGE != 0 {
+            let gpa = (pde as usize & PTE_ADDR_MASK as usize & !0x1f_ffff) | (gva & 0x1f_ffff);
+            return Ok((gpa, flags));
+        }
+
+        let pt_gpa = pde as usize & PTE_ADDR_MASK as usize;
+        let pte = self.read_guest_u64(pt_gpa + ((gva >> 12) & 0x1ff) * 8)?;
+        if pte & PTE_PRESENT == 0 {
+            return Err(HvError::BadState);
+        }
+        if pte & PTE_RESERVED_MASK != 0 {
+            return Err(HvError::BadState);
+        }
+        accumulate_flags(&mut flags, pte);
+        Ok((
+            (pte as usize & PTE_ADDR_MASK as usize) | (gva & (PAGE_SIZE - 1)),
+            flags,
+        ))
+    }
+
+    /// Legacy 2-level 32-bit walk (no PAE). Entries are only 32 bits wide
+    /// with no NX bit and no address bits above bit 31, so there's no
+    /// equivalent of the 64-bit reserved-bit range to police here beyond
+    /// the present check above.
+    fn translate_gva_32(&self, gva: usize, cr3: usize) -> HvResult<(usize, MemFlags)> {
+        let pd_gpa = cr3 & 0xffff_f000;
+        let pde = self.read_guest_u32(pd_gpa + ((gva >> 22) & 0x3ff) * 4)? as u64;
+        if pde & PTE_PRESENT == 0 {
+            return Err(HvError::BadState);
+        }
+
+        let mut flags = MemFlags::READ | MemFlags::WRITE | MemFlags::EXECUTE | MemFlags::USER;
+        accumulate_flags_32(&mut flags, pde);
+        if pde & PTE_HUGE != 0 {
+            let gpa = (pde as usize & 0xffc0_0000) | (gva & 0x3f_ffff);
+            return Ok((gpa, flags));
+        }
+
+        let pt_gpa = pde as usize & 0xffff_f000;
+        let pte = self.read_guest_u32(pt_gpa + ((gva >> 12) & 0x3ff) * 4)? as u64;
+        if pte & PTE_PRESENT == 0 {
+            return Err(HvError::BadState);
+        }
+        accumulate_flags_32(&mut flags, pte);
+        Ok((
+            (pte as usize & 0xffff_f000) | (gva & (PAGE_SIZE - 1)),
+            flags,
+        ))
+    }
+}
+
+/// Intersect the running R/W/U/NX flags with what this (64-bit) entry
+/// allows: a single non-writable or NX entry anywhere in the walk makes
+/// the whole translation non-writable/non-executable.
+fn accumulate_flags(flags: &mut MemFlags, entry: u64) {
+    if entry & PTE_WRITABLE == 0 {
+        *flags -= MemFlags::WRITE;
+    }
+    if entry & PTE_USER == 0 {
+        *flags -= MemFlags::USER;
+    }
+    if entry & PTE_NX != 0 {
+        *flags -= MemFlags::EXECUTE;
+    }
+}
+
+fn accumulate_flags_32(flags: &mut MemFlags, entry: u64) {
+    if entry & PTE_WRITABLE == 0 {
+        *flags -= MemFlags::WRITE;
+    }
+    if entry & PTE_USER == 0 {
+        *flags -= MemFlags::USER;
+    }
+}